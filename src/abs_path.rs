@@ -0,0 +1,163 @@
+//! A typed distinction between absolute and possibly-relative paths.
+//!
+//! `imfs`, `path_map`, and `SnapshotMiddleware` all deal exclusively in
+//! canonical absolute paths internally; bugs where a relative path slipped
+//! in (especially around symlinks and case-insensitive filesystems) used to
+//! only show up as confusing watcher/session misbehavior at runtime. Making
+//! the distinction part of the type system turns those into compile errors.
+
+use std::{
+    borrow::Borrow,
+    ops::Deref,
+    path::{Path, PathBuf},
+};
+
+/// A borrowed path that's guaranteed to be absolute. Works like `Path`, but
+/// can't be constructed from a relative path.
+#[repr(transparent)]
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub struct AbsPath(Path);
+
+impl AbsPath {
+    /// Wraps an already-known-absolute `Path` without checking. Used
+    /// internally by `AbsPathBuf::deref` and friends, where the path was
+    /// already validated when the owning `AbsPathBuf` was constructed.
+    fn new_unchecked(path: &Path) -> &AbsPath {
+        unsafe { &*(path as *const Path as *const AbsPath) }
+    }
+
+    pub fn as_path(&self) -> &Path {
+        &self.0
+    }
+
+    pub fn to_path_buf(&self) -> AbsPathBuf {
+        AbsPathBuf(self.0.to_path_buf())
+    }
+}
+
+impl AsRef<Path> for AbsPath {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl PartialEq<AbsPathBuf> for AbsPath {
+    fn eq(&self, other: &AbsPathBuf) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl ToOwned for AbsPath {
+    type Owned = AbsPathBuf;
+
+    fn to_owned(&self) -> AbsPathBuf {
+        self.to_path_buf()
+    }
+}
+
+/// An owned path that's guaranteed to be absolute.
+///
+/// Construct one with `AbsPathBuf::try_from`, which rejects relative paths,
+/// or `AbsPathBuf::assert`, which panics if given one. `assert` exists for
+/// call sites that already know (from an internal invariant, like a path
+/// that just came back from `fs::canonicalize`) that the path must be
+/// absolute, and would rather fail loudly than thread a `Result` through
+/// code that can't meaningfully recover from the alternative.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AbsPathBuf(PathBuf);
+
+impl AbsPathBuf {
+    pub fn try_from(path: PathBuf) -> Result<Self, PathBuf> {
+        if path.is_absolute() {
+            Ok(Self(path))
+        } else {
+            Err(path)
+        }
+    }
+
+    pub fn assert(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+
+        match Self::try_from(path) {
+            Ok(abs_path) => abs_path,
+            Err(path) => panic!(
+                "expected an absolute path, but got a relative one: {}",
+                path.display()
+            ),
+        }
+    }
+
+    pub fn as_abs_path(&self) -> &AbsPath {
+        self
+    }
+
+    pub fn into_path_buf(self) -> PathBuf {
+        self.0
+    }
+
+    pub fn join(&self, child: impl AsRef<Path>) -> AbsPathBuf {
+        AbsPathBuf(self.0.join(child))
+    }
+}
+
+impl Deref for AbsPathBuf {
+    type Target = AbsPath;
+
+    fn deref(&self) -> &AbsPath {
+        AbsPath::new_unchecked(&self.0)
+    }
+}
+
+impl Borrow<AbsPath> for AbsPathBuf {
+    fn borrow(&self) -> &AbsPath {
+        self
+    }
+}
+
+impl AsRef<Path> for AbsPathBuf {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<AbsPath> for AbsPathBuf {
+    fn as_ref(&self) -> &AbsPath {
+        self
+    }
+}
+
+impl PartialEq<AbsPath> for AbsPathBuf {
+    fn eq(&self, other: &AbsPath) -> bool {
+        self.0 == other.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn try_from_rejects_relative_paths() {
+        assert!(AbsPathBuf::try_from(PathBuf::from("relative/path")).is_err());
+    }
+
+    #[test]
+    fn try_from_accepts_absolute_paths() {
+        assert!(AbsPathBuf::try_from(PathBuf::from("/absolute/path")).is_ok());
+    }
+
+    #[test]
+    #[should_panic(expected = "relative")]
+    fn assert_panics_on_relative_paths() {
+        AbsPathBuf::assert("relative/path");
+    }
+
+    #[test]
+    fn deref_and_equality_round_trip() {
+        let abs_path_buf = AbsPathBuf::assert("/foo/bar");
+        let abs_path: &AbsPath = &abs_path_buf;
+
+        assert_eq!(abs_path, &abs_path_buf);
+        assert_eq!(abs_path.as_path(), Path::new("/foo/bar"));
+    }
+}
@@ -0,0 +1,36 @@
+//! An in-memory filesystem abstraction that Rojo uses to watch and snapshot
+//! real filesystem trees without hitting disk more than necessary.
+
+use std::{fmt, io, path::PathBuf};
+
+pub mod new;
+mod overlay;
+pub mod store;
+
+pub type FsResult<T> = Result<T, FsError>;
+
+/// An I/O error tagged with the path that caused it, so callers further up
+/// the stack don't have to thread the path through separately.
+#[derive(Debug)]
+pub struct FsError {
+    path: PathBuf,
+    inner: io::Error,
+}
+
+impl FsError {
+    pub fn new(path: PathBuf, inner: io::Error) -> Self {
+        Self { path, inner }
+    }
+}
+
+impl fmt::Display for FsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.inner)
+    }
+}
+
+impl std::error::Error for FsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.inner)
+    }
+}
@@ -0,0 +1,473 @@
+//! The "new" (content-addressed) generation of the in-memory filesystem.
+//!
+//! `Imfs<F>` is generic over an `ImfsFetcher`, which is the thing that
+//! actually knows how to talk to a real filesystem (or a fake one, in
+//! tests). `Imfs` itself is responsible for caching metadata and owning the
+//! `ContentStore` that file bodies are deduplicated into.
+
+use std::{collections::HashMap, sync::Arc};
+
+use crate::abs_path::{AbsPath, AbsPathBuf};
+
+use super::{
+    overlay::{Overlay, OverlayEntry},
+    store::{ContentHash, ContentStore},
+    FsError, FsResult,
+};
+
+/// Knows how to read a real (or fake) filesystem. Implementors don't need to
+/// worry about caching or deduplication; `Imfs` handles that on top.
+///
+/// Every path that crosses this boundary is an `AbsPath`: the `Imfs` never
+/// resolves relative paths on a fetcher's behalf, so a fetcher can assume
+/// the paths it's given are already canonical and absolute.
+pub trait ImfsFetcher {
+    fn read_metadata(&mut self, path: &AbsPath) -> FsResult<ImfsMetadata>;
+    fn read_children(&mut self, path: &AbsPath) -> FsResult<Vec<AbsPathBuf>>;
+    fn read_contents(&mut self, path: &AbsPath) -> FsResult<Vec<u8>>;
+
+    /// Fetches the bytes for a piece of content previously handed out as a
+    /// `ContentHash`, without needing to know which path(s) it currently
+    /// lives at. Fetchers that don't keep their own content-addressed cache
+    /// can implement this in terms of `read_contents` plus a lookup of one
+    /// of the paths that produced the hash.
+    fn read_contents_at_hash(&mut self, hash: &ContentHash) -> FsResult<Arc<Vec<u8>>>;
+
+    fn watch(&mut self, path: &AbsPath);
+    fn unwatch(&mut self, path: &AbsPath);
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ImfsMetadata {
+    pub is_file: bool,
+}
+
+/// A handle to a single path inside an `Imfs`. Cheap to clone; the actual
+/// file contents live in the `Imfs`'s `ContentStore` and are referenced by
+/// digest rather than copied around.
+#[derive(Debug, Clone)]
+pub struct ImfsEntry {
+    pub path: AbsPathBuf,
+    pub is_file: bool,
+    pub children: Vec<AbsPathBuf>,
+
+    /// `Some` for files, whose contents are read and interned into the
+    /// owning `Imfs`'s `ContentStore` when the entry is first fetched.
+    /// `None` for directories.
+    pub(crate) content_hash: Option<ContentHash>,
+
+    /// Whether this entry came from a real `ImfsFetcher::watch` call.
+    /// Overlay-sourced entries never call `watch` (there's nothing on disk
+    /// to watch), so `forget` must only call `unwatch` for entries where
+    /// this is `true`.
+    pub(crate) watched: bool,
+}
+
+impl ImfsEntry {
+    pub fn path(&self) -> &AbsPath {
+        &self.path
+    }
+}
+
+/// A snapshot of a single file's contents, ready to be handed to a
+/// `SnapshotMiddleware`. Holds a digest into the owning `Imfs`'s
+/// `ContentStore` rather than an inline copy of the bytes, so that many
+/// `ImfsSnapshot`s for identical files share one buffer.
+///
+/// Holds its own reference to the store it was interned into and releases it
+/// on drop, the same way `ImfsEntry`'s hash is released by `Imfs::forget` --
+/// otherwise every write-back through this type would grow the store by one
+/// entry that nothing ever frees.
+pub struct ImfsSnapshot {
+    content_hash: ContentHash,
+    contents: Arc<Vec<u8>>,
+    store: Arc<ContentStore>,
+}
+
+impl std::fmt::Debug for ImfsSnapshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ImfsSnapshot")
+            .field("content_hash", &self.content_hash)
+            .field("contents", &self.contents)
+            .finish()
+    }
+}
+
+impl ImfsSnapshot {
+    pub fn new(contents: Vec<u8>, store: &Arc<ContentStore>) -> Self {
+        let content_hash = store.insert_checked(contents);
+        let contents = store
+            .get(&content_hash)
+            .expect("just-inserted content must be present in the store");
+
+        Self {
+            content_hash,
+            contents,
+            store: Arc::clone(store),
+        }
+    }
+
+    pub fn content_hash(&self) -> ContentHash {
+        self.content_hash
+    }
+
+    pub fn contents(&self) -> &[u8] {
+        &self.contents
+    }
+}
+
+impl Clone for ImfsSnapshot {
+    fn clone(&self) -> Self {
+        self.store.retain(&self.content_hash);
+
+        Self {
+            content_hash: self.content_hash,
+            contents: Arc::clone(&self.contents),
+            store: Arc::clone(&self.store),
+        }
+    }
+}
+
+impl Drop for ImfsSnapshot {
+    fn drop(&mut self) {
+        self.store.release(&self.content_hash);
+    }
+}
+
+/// The in-memory filesystem itself. Wraps an `ImfsFetcher` with a cache of
+/// metadata and a `ContentStore` that deduplicates file bodies by hash.
+pub struct Imfs<F> {
+    fetcher: F,
+    store: Arc<ContentStore>,
+    cache: HashMap<AbsPathBuf, ImfsEntry>,
+    overlay: Overlay,
+}
+
+impl<F: ImfsFetcher> Imfs<F> {
+    pub fn new(fetcher: F) -> Self {
+        Self {
+            fetcher,
+            store: Arc::new(ContentStore::new()),
+            cache: HashMap::new(),
+            overlay: Overlay::new(),
+        }
+    }
+
+    pub fn store(&self) -> &Arc<ContentStore> {
+        &self.store
+    }
+
+    /// Shadows `path` with `contents` so that every read of it (including
+    /// ones routed through `SnapshotMiddleware::from_imfs`) sees `contents`
+    /// instead of whatever is on disk, without writing anything to disk.
+    /// Used to live-sync unsaved editor buffers.
+    pub fn set_overlay(&mut self, path: &AbsPath, contents: Vec<u8>) {
+        self.forget(path);
+        self.overlay.set(path.to_path_buf(), contents);
+    }
+
+    /// Shadows `path` as deleted, even if it still exists on disk. Covers
+    /// the case where an overlaid file is deleted on disk while the overlay
+    /// for it is still live: the overlay stays authoritative until the
+    /// client explicitly clears it.
+    pub fn set_overlay_deleted(&mut self, path: &AbsPath) {
+        self.forget(path);
+        self.overlay.set_deleted(path.to_path_buf());
+    }
+
+    /// Removes any overlay for `path`, letting subsequent reads fall back to
+    /// the underlying `ImfsFetcher`.
+    pub fn clear_overlay(&mut self, path: &AbsPath) {
+        self.overlay.clear(path);
+        self.forget(path);
+    }
+
+    /// Whether `path` is currently shadowed by an overlay.
+    ///
+    /// This is meant as the hook a filesystem watcher would use to decide
+    /// whether an incoming disk-change event should be deferred or merged
+    /// instead of firing immediately, so that a real disk write which merely
+    /// matches the overlay doesn't double-fire a change. That wiring doesn't
+    /// exist in this tree yet -- there is no watcher implementation here to
+    /// wire it into -- so this is currently unused outside of tests.
+    pub fn is_overlaid(&self, path: &AbsPath) -> bool {
+        self.overlay.contains(path)
+    }
+
+    /// Returns the cached entry for `path`, fetching and interning its
+    /// contents (if it's a file) on first access. Overlay entries, if
+    /// present, take priority over both the cache and the underlying
+    /// `ImfsFetcher`.
+    pub fn get(&mut self, path: &AbsPath) -> FsResult<ImfsEntry> {
+        if let Some(entry) = self.cache.get(path) {
+            return Ok(entry.clone());
+        }
+
+        if let Some(overlay_entry) = self.overlay.get(path) {
+            return match overlay_entry.clone() {
+                OverlayEntry::Contents(contents) => {
+                    let entry = ImfsEntry {
+                        path: path.to_path_buf(),
+                        is_file: true,
+                        children: Vec::new(),
+                        content_hash: Some(self.store.insert_checked(contents)),
+                        watched: false,
+                    };
+
+                    self.cache.insert(path.to_path_buf(), entry.clone());
+                    Ok(entry)
+                }
+                OverlayEntry::Deleted => Err(FsError::new(
+                    path.to_path_buf().into_path_buf(),
+                    not_found(),
+                )),
+            };
+        }
+
+        let metadata = self.fetcher.read_metadata(path)?;
+
+        let (children, content_hash) = if metadata.is_file {
+            let contents = self.fetcher.read_contents(path)?;
+            (Vec::new(), Some(self.store.insert_checked(contents)))
+        } else {
+            (self.fetcher.read_children(path)?, None)
+        };
+
+        let entry = ImfsEntry {
+            path: path.to_path_buf(),
+            is_file: metadata.is_file,
+            children,
+            content_hash,
+            watched: true,
+        };
+
+        self.cache.insert(path.to_path_buf(), entry.clone());
+        self.fetcher.watch(path);
+
+        Ok(entry)
+    }
+
+    /// Returns the (deduplicated) contents backing a file entry.
+    pub fn contents(&mut self, entry: &ImfsEntry) -> FsResult<Arc<Vec<u8>>> {
+        let hash = entry
+            .content_hash
+            .ok_or_else(|| FsError::new(entry.path.as_abs_path().as_path().to_path_buf(), not_a_file()))?;
+
+        match self.store.get(&hash) {
+            Some(contents) => Ok(contents),
+            None => self.fetcher.read_contents_at_hash(&hash),
+        }
+    }
+
+    /// Drops the cached entry for `path`, releasing its reference (if any)
+    /// on the content store so unreferenced file bodies can eventually be
+    /// freed.
+    pub fn forget(&mut self, path: &AbsPath) {
+        if let Some(entry) = self.cache.remove(path) {
+            if let Some(hash) = entry.content_hash {
+                self.store.release(&hash);
+            }
+            if entry.watched {
+                self.fetcher.unwatch(path);
+            }
+        }
+    }
+
+    /// Walks the tree rooted at `path` depth-first, calling `visit` for
+    /// every entry reached. Directories for which `should_descend` returns
+    /// `false` are not read at all, so a project root's `exclude` globs can
+    /// prune whole subtrees before the fetcher ever touches them.
+    pub fn walk_filtered(
+        &mut self,
+        path: &AbsPath,
+        should_descend: &mut impl FnMut(&AbsPath) -> bool,
+        visit: &mut impl FnMut(&ImfsEntry),
+    ) -> FsResult<()> {
+        let entry = self.get(path)?;
+        visit(&entry);
+
+        if entry.is_file {
+            return Ok(());
+        }
+
+        for child in entry.children {
+            if should_descend(&child) {
+                self.walk_filtered(&child, should_descend, visit)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn not_a_file() -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, "entry is not a file")
+}
+
+fn not_found() -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::NotFound, "path does not exist")
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashMap as StdHashMap, path::PathBuf};
+
+    use super::*;
+
+    #[derive(Default)]
+    struct FakeFetcher {
+        files: StdHashMap<AbsPathBuf, Vec<u8>>,
+        unwatched: Vec<AbsPathBuf>,
+    }
+
+    impl ImfsFetcher for FakeFetcher {
+        fn read_metadata(&mut self, path: &AbsPath) -> FsResult<ImfsMetadata> {
+            if self.files.contains_key(path) {
+                Ok(ImfsMetadata { is_file: true })
+            } else {
+                Err(FsError::new(path.to_path_buf().into_path_buf(), not_found()))
+            }
+        }
+
+        fn read_children(&mut self, _path: &AbsPath) -> FsResult<Vec<AbsPathBuf>> {
+            Ok(Vec::new())
+        }
+
+        fn read_contents(&mut self, path: &AbsPath) -> FsResult<Vec<u8>> {
+            self.files
+                .get(path)
+                .cloned()
+                .ok_or_else(|| FsError::new(path.to_path_buf().into_path_buf(), not_found()))
+        }
+
+        fn read_contents_at_hash(&mut self, hash: &ContentHash) -> FsResult<Arc<Vec<u8>>> {
+            Err(FsError::new(
+                PathBuf::from(format!("<missing content for {:?}>", hash)),
+                not_found(),
+            ))
+        }
+
+        fn watch(&mut self, _path: &AbsPath) {}
+        fn unwatch(&mut self, path: &AbsPath) {
+            self.unwatched.push(path.to_path_buf());
+        }
+    }
+
+    fn path(s: &str) -> AbsPathBuf {
+        AbsPathBuf::assert(s)
+    }
+
+    #[test]
+    fn overlay_shadows_disk_contents() {
+        let mut fetcher = FakeFetcher::default();
+        let target = path("/project/src/init.lua");
+        fetcher.files.insert(target.clone(), b"on disk".to_vec());
+
+        let mut imfs = Imfs::new(fetcher);
+        imfs.set_overlay(&target, b"unsaved edit".to_vec());
+
+        let entry = imfs.get(&target).unwrap();
+        let contents = imfs.contents(&entry).unwrap();
+
+        assert_eq!(contents.as_slice(), b"unsaved edit");
+    }
+
+    #[test]
+    fn clear_overlay_falls_back_to_disk() {
+        let mut fetcher = FakeFetcher::default();
+        let target = path("/project/src/init.lua");
+        fetcher.files.insert(target.clone(), b"on disk".to_vec());
+
+        let mut imfs = Imfs::new(fetcher);
+        imfs.set_overlay(&target, b"unsaved edit".to_vec());
+        imfs.clear_overlay(&target);
+
+        let entry = imfs.get(&target).unwrap();
+        let contents = imfs.contents(&entry).unwrap();
+
+        assert_eq!(contents.as_slice(), b"on disk");
+    }
+
+    #[test]
+    fn overlay_deleted_is_reported_as_missing_even_though_disk_still_has_it() {
+        let mut fetcher = FakeFetcher::default();
+        let target = path("/project/src/init.lua");
+        fetcher.files.insert(target.clone(), b"still on disk".to_vec());
+
+        let mut imfs = Imfs::new(fetcher);
+        imfs.set_overlay_deleted(&target);
+
+        assert!(imfs.get(&target).is_err());
+    }
+
+    #[test]
+    fn replacing_an_overlay_releases_the_previous_content_store_entry() {
+        let mut fetcher = FakeFetcher::default();
+        let target = path("/project/src/init.lua");
+        fetcher.files.insert(target.clone(), b"on disk".to_vec());
+
+        let mut imfs = Imfs::new(fetcher);
+
+        imfs.set_overlay(&target, b"first edit".to_vec());
+        imfs.get(&target).unwrap();
+        assert_eq!(imfs.store().len(), 1);
+
+        imfs.set_overlay(&target, b"second edit".to_vec());
+        imfs.get(&target).unwrap();
+        assert_eq!(
+            imfs.store().len(),
+            1,
+            "the content from the first edit should have been released, not leaked"
+        );
+
+        imfs.clear_overlay(&target);
+        assert_eq!(
+            imfs.store().len(),
+            0,
+            "clearing the overlay should release the last edit's content too"
+        );
+    }
+
+    #[test]
+    fn forgetting_an_overlay_only_path_does_not_unwatch_it() {
+        let fetcher = FakeFetcher::default();
+        let target = path("/project/src/init.lua");
+
+        let mut imfs = Imfs::new(fetcher);
+        imfs.set_overlay(&target, b"unsaved edit".to_vec());
+        imfs.get(&target).unwrap();
+
+        // Clearing the overlay calls `forget` on a path this `Imfs` never
+        // called `watch` for (it only ever existed in the overlay), so it
+        // must not be unwatched.
+        imfs.clear_overlay(&target);
+
+        assert!(imfs.fetcher.unwatched.is_empty());
+    }
+
+    #[test]
+    fn dropping_a_snapshot_releases_its_content_store_entry() {
+        let store = Arc::new(ContentStore::new());
+
+        let snapshot = ImfsSnapshot::new(b"return {}".to_vec(), &store);
+        assert_eq!(store.len(), 1);
+
+        drop(snapshot);
+        assert_eq!(store.len(), 0);
+    }
+
+    #[test]
+    fn cloning_a_snapshot_keeps_the_entry_alive_until_every_clone_is_dropped() {
+        let store = Arc::new(ContentStore::new());
+
+        let snapshot = ImfsSnapshot::new(b"return {}".to_vec(), &store);
+        let cloned = snapshot.clone();
+        assert_eq!(store.len(), 1);
+
+        drop(snapshot);
+        assert_eq!(store.len(), 1, "the clone should still hold a reference");
+
+        drop(cloned);
+        assert_eq!(store.len(), 0);
+    }
+}
@@ -0,0 +1,89 @@
+//! The overlay layer that lets a client (a Studio plugin or editor bridge
+//! over the `web` API, say) inject in-memory file contents that shadow what
+//! is actually on disk, so unsaved edits can be synced live without being
+//! written to disk first.
+
+use std::collections::HashMap;
+
+use crate::abs_path::{AbsPath, AbsPathBuf};
+
+#[derive(Debug, Clone)]
+pub(crate) enum OverlayEntry {
+    Contents(Vec<u8>),
+    Deleted,
+}
+
+/// The set of paths currently shadowed by in-memory content. Consulted by
+/// `Imfs::get` before falling through to the underlying `ImfsFetcher`.
+#[derive(Debug, Default)]
+pub(crate) struct Overlay {
+    entries: HashMap<AbsPathBuf, OverlayEntry>,
+}
+
+impl Overlay {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(&mut self, path: AbsPathBuf, contents: Vec<u8>) {
+        self.entries.insert(path, OverlayEntry::Contents(contents));
+    }
+
+    pub fn set_deleted(&mut self, path: AbsPathBuf) {
+        self.entries.insert(path, OverlayEntry::Deleted);
+    }
+
+    pub fn clear(&mut self, path: &AbsPath) -> Option<OverlayEntry> {
+        self.entries.remove(path)
+    }
+
+    pub fn get(&self, path: &AbsPath) -> Option<&OverlayEntry> {
+        self.entries.get(path)
+    }
+
+    pub fn contains(&self, path: &AbsPath) -> bool {
+        self.entries.contains_key(path)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn path(s: &str) -> AbsPathBuf {
+        AbsPathBuf::assert(s)
+    }
+
+    #[test]
+    fn set_then_get_returns_the_shadowed_contents() {
+        let mut overlay = Overlay::new();
+        overlay.set(path("/project/src/init.lua"), b"unsaved".to_vec());
+
+        match overlay.get(path("/project/src/init.lua").as_abs_path()) {
+            Some(OverlayEntry::Contents(contents)) => assert_eq!(contents, b"unsaved"),
+            other => panic!("expected Contents, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn set_deleted_then_get_reports_deleted() {
+        let mut overlay = Overlay::new();
+        overlay.set_deleted(path("/project/src/init.lua"));
+
+        match overlay.get(path("/project/src/init.lua").as_abs_path()) {
+            Some(OverlayEntry::Deleted) => {}
+            other => panic!("expected Deleted, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn clear_removes_the_overlay() {
+        let mut overlay = Overlay::new();
+        let target = path("/project/src/init.lua");
+        overlay.set(target.clone(), b"unsaved".to_vec());
+
+        overlay.clear(target.as_abs_path());
+
+        assert!(!overlay.contains(target.as_abs_path()));
+    }
+}
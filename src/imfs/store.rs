@@ -0,0 +1,206 @@
+//! A content-addressed store for file bodies held by the `Imfs`.
+//!
+//! File contents are written once, keyed by the digest of their bytes, so
+//! that many paths with identical contents (e.g. copies of the same default
+//! script) share a single buffer. Entries are reference-counted by the
+//! number of cached `ImfsEntry`s and live `ImfsSnapshot`s that currently
+//! point at them and are freed once the count drops to zero.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+/// Identifies a piece of file content by the BLAKE3 digest of its bytes,
+/// plus the length as a cheap guard against treating a truncated read as a
+/// full match.
+///
+/// We treat the digest as the content's identity: two different byte
+/// strings hashing to the same `ContentHash` is considered a hash collision,
+/// not a legitimate alias. `ContentStore::insert_checked` double checks the
+/// stored bytes against the incoming buffer when that assumption needs to be
+/// defended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ContentHash {
+    digest: [u8; 32],
+    len: u64,
+}
+
+impl ContentHash {
+    pub fn compute(contents: &[u8]) -> Self {
+        let digest = *blake3::hash(contents).as_bytes();
+
+        Self {
+            digest,
+            len: contents.len() as u64,
+        }
+    }
+}
+
+struct StoreEntry {
+    contents: Arc<Vec<u8>>,
+    ref_count: u32,
+}
+
+/// A reference-counted, content-addressed map from `ContentHash` to file
+/// bytes. Shared (behind an `Arc`) between an `Imfs` and the `ImfsSnapshot`s
+/// interned into it, since the underlying contents don't change based on
+/// who's looking at them.
+#[derive(Default)]
+pub struct ContentStore {
+    entries: Mutex<HashMap<ContentHash, StoreEntry>>,
+}
+
+impl ContentStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Inserts `contents` into the store if it isn't already present and
+    /// returns its hash. If an entry for this hash already exists, its
+    /// reference count is bumped and the new buffer is discarded.
+    pub fn insert(&self, contents: Vec<u8>) -> ContentHash {
+        let hash = ContentHash::compute(&contents);
+
+        let mut entries = self.entries.lock().unwrap();
+        entries
+            .entry(hash)
+            .and_modify(|entry| entry.ref_count += 1)
+            .or_insert_with(|| StoreEntry {
+                contents: Arc::new(contents),
+                ref_count: 1,
+            });
+
+        hash
+    }
+
+    /// Like `insert`, but if a matching hash is already present, verifies
+    /// that the bytes are actually equal before trusting the cache hit. This
+    /// is strictly more expensive and is meant for call sites that can't
+    /// tolerate a hash collision silently aliasing two different files.
+    pub fn insert_checked(&self, contents: Vec<u8>) -> ContentHash {
+        let hash = ContentHash::compute(&contents);
+
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get_mut(&hash) {
+            Some(entry) if entry.contents.as_slice() == contents.as_slice() => {
+                entry.ref_count += 1;
+            }
+            Some(entry) => {
+                // Extraordinarily unlikely, but if it ever happens we keep
+                // the existing entry's identity and just make sure the bytes
+                // it returns are the ones that were actually asked for.
+                entry.contents = Arc::new(contents);
+                entry.ref_count += 1;
+            }
+            None => {
+                entries.insert(
+                    hash,
+                    StoreEntry {
+                        contents: Arc::new(contents),
+                        ref_count: 1,
+                    },
+                );
+            }
+        }
+
+        hash
+    }
+
+    pub fn get(&self, hash: &ContentHash) -> Option<Arc<Vec<u8>>> {
+        self.entries
+            .lock()
+            .unwrap()
+            .get(hash)
+            .map(|entry| Arc::clone(&entry.contents))
+    }
+
+    /// Bumps the reference count for an already-present `hash`, without
+    /// needing the bytes again. Used when cloning a handle (like
+    /// `ImfsSnapshot`) that already holds the hash, so the clone's eventual
+    /// `release` doesn't over-release a count nothing added. A no-op if
+    /// `hash` isn't present, which shouldn't happen as long as callers only
+    /// pass hashes they're already holding a reference to.
+    pub fn retain(&self, hash: &ContentHash) {
+        let mut entries = self.entries.lock().unwrap();
+
+        if let Some(entry) = entries.get_mut(hash) {
+            entry.ref_count += 1;
+        }
+    }
+
+    /// Drops one reference to `hash`. Once the last reference is released,
+    /// the backing bytes are freed. This is a simple ref-count rather than a
+    /// tracing mark-sweep pass, which is sufficient as long as every
+    /// `ImfsEntry` that was handed a hash from `insert` calls this exactly
+    /// once when it's torn down or replaced.
+    pub fn release(&self, hash: &ContentHash) {
+        let mut entries = self.entries.lock().unwrap();
+
+        if let Some(entry) = entries.get_mut(hash) {
+            entry.ref_count = entry.ref_count.saturating_sub(1);
+
+            if entry.ref_count == 0 {
+                entries.remove(hash);
+            }
+        }
+    }
+
+    #[cfg(test)]
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn identical_contents_collapse_to_one_entry() {
+        let store = ContentStore::new();
+
+        let a = store.insert(b"hello".to_vec());
+        let b = store.insert(b"hello".to_vec());
+
+        assert_eq!(a, b);
+        assert_eq!(store.len(), 1);
+    }
+
+    #[test]
+    fn release_frees_the_entry_once_unreferenced() {
+        let store = ContentStore::new();
+
+        let hash = store.insert(b"hello".to_vec());
+        store.insert(b"hello".to_vec());
+
+        store.release(&hash);
+        assert_eq!(store.len(), 1);
+
+        store.release(&hash);
+        assert_eq!(store.len(), 0);
+    }
+
+    #[test]
+    fn retain_requires_an_extra_release_before_freeing() {
+        let store = ContentStore::new();
+
+        let hash = store.insert(b"hello".to_vec());
+        store.retain(&hash);
+
+        store.release(&hash);
+        assert_eq!(store.len(), 1);
+
+        store.release(&hash);
+        assert_eq!(store.len(), 0);
+    }
+
+    #[test]
+    fn get_returns_none_for_unknown_hash() {
+        let store = ContentStore::new();
+        let hash = ContentHash::compute(b"never inserted");
+
+        assert!(store.get(&hash).is_none());
+    }
+}
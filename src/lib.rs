@@ -0,0 +1,9 @@
+#[macro_use]
+extern crate serde_derive;
+
+pub mod abs_path;
+pub mod imfs;
+pub mod path_map;
+pub mod project;
+pub mod snapshot;
+pub mod snapshot_middleware;
@@ -0,0 +1,98 @@
+//! A map keyed by absolute filesystem paths, with support for looking up
+//! every entry nested under a given path. Used to track, for instance,
+//! every `RbxId` that was produced from a given subtree of the `Imfs`.
+
+use std::collections::HashMap;
+
+use crate::abs_path::{AbsPath, AbsPathBuf};
+
+#[derive(Debug, Default)]
+pub struct PathMap<V> {
+    map: HashMap<AbsPathBuf, V>,
+}
+
+impl<V> PathMap<V> {
+    pub fn new() -> Self {
+        Self {
+            map: HashMap::new(),
+        }
+    }
+
+    pub fn insert(&mut self, path: AbsPathBuf, value: V) -> Option<V> {
+        self.map.insert(path, value)
+    }
+
+    pub fn get(&self, path: &AbsPath) -> Option<&V> {
+        self.map.get(path)
+    }
+
+    pub fn remove(&mut self, path: &AbsPath) -> Option<V> {
+        self.map.remove(path)
+    }
+
+    pub fn contains_key(&self, path: &AbsPath) -> bool {
+        self.map.contains_key(path)
+    }
+
+    /// Returns every entry whose path is `path` itself or nested under it.
+    pub fn descendants<'a>(&'a self, path: &'a AbsPath) -> impl Iterator<Item = (&'a AbsPathBuf, &'a V)> {
+        self.map
+            .iter()
+            .filter(move |(entry_path, _)| entry_path.as_abs_path().as_path().starts_with(path.as_path()))
+    }
+
+    /// Removes every entry whose path is `path` itself or nested under it,
+    /// returning the removed paths.
+    pub fn remove_descendants(&mut self, path: &AbsPath) -> Vec<AbsPathBuf> {
+        let to_remove: Vec<AbsPathBuf> = self
+            .descendants(path)
+            .map(|(entry_path, _)| entry_path.clone())
+            .collect();
+
+        for entry_path in &to_remove {
+            self.map.remove(entry_path);
+        }
+
+        to_remove
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn abs(path: &str) -> AbsPathBuf {
+        AbsPathBuf::assert(path)
+    }
+
+    #[test]
+    fn descendants_includes_the_path_itself() {
+        let mut map = PathMap::new();
+        map.insert(abs("/project/src"), 1);
+        map.insert(abs("/project/src/init.lua"), 2);
+        map.insert(abs("/project/other"), 3);
+
+        let found: Vec<_> = map
+            .descendants(abs("/project/src").as_abs_path())
+            .map(|(_, v)| *v)
+            .collect();
+
+        assert_eq!(found.len(), 2);
+        assert!(found.contains(&1));
+        assert!(found.contains(&2));
+    }
+
+    #[test]
+    fn remove_descendants_clears_the_subtree() {
+        let mut map = PathMap::new();
+        map.insert(abs("/project/src"), 1);
+        map.insert(abs("/project/src/init.lua"), 2);
+        map.insert(abs("/project/other"), 3);
+
+        map.remove_descendants(abs("/project/src").as_abs_path());
+
+        assert!(map.get(abs("/project/src").as_abs_path()).is_none());
+        assert!(map.get(abs("/project/src/init.lua").as_abs_path()).is_none());
+        assert!(map.get(abs("/project/other").as_abs_path()).is_some());
+    }
+}
@@ -0,0 +1,369 @@
+//! Project files describe the tree of Roblox instances that Rojo produces
+//! from a filesystem, including which files on disk make up each node.
+
+use std::{collections::HashMap, path::PathBuf};
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+
+use crate::{
+    abs_path::{AbsPath, AbsPathBuf},
+    imfs::{
+        new::{Imfs, ImfsEntry, ImfsFetcher},
+        FsResult,
+    },
+    snapshot_middleware::SnapshotMiddleware,
+};
+
+/// One node of a project file's instance tree. A node with a `$path` pulls
+/// its contents from disk; `include`/`exclude` narrow that down to a subset
+/// of the root's files, the same way a package manifest's `include`/
+/// `exclude` lists narrow down a bare folder to the files that actually ship.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ProjectNode {
+    #[serde(rename = "$path")]
+    pub path: Option<PathBuf>,
+
+    /// Glob patterns (relative to `path`) that a file must match to be
+    /// synced. An empty list means "everything", matching today's implicit
+    /// take-everything-under-the-path behavior.
+    #[serde(rename = "$include", default)]
+    pub include: Vec<String>,
+
+    /// Glob patterns (relative to `path`) that are not synced even when they
+    /// would otherwise match `include`. Directories matching an exclude
+    /// pattern are pruned entirely rather than walked and filtered file by
+    /// file.
+    #[serde(rename = "$exclude", default)]
+    pub exclude: Vec<String>,
+
+    #[serde(flatten)]
+    pub children: HashMap<String, ProjectNode>,
+}
+
+/// The compiled, ready-to-evaluate form of a `ProjectNode`'s `$path` plus its
+/// glob lists, anchored to an absolute path on disk. The globs are compiled
+/// once, in `ResolvedRoot::new`; `walk_included_files` and
+/// `filter_changed_paths` then evaluate them without recompiling per file.
+pub struct ResolvedRoot {
+    path: AbsPathBuf,
+    include: GlobSet,
+    exclude: GlobSet,
+}
+
+#[derive(Debug)]
+pub struct InvalidGlob {
+    pub pattern: String,
+    pub source: globset::Error,
+}
+
+impl ResolvedRoot {
+    pub fn new(path: AbsPathBuf, node: &ProjectNode) -> Result<Self, InvalidGlob> {
+        Ok(Self {
+            path,
+            include: build_glob_set(&node.include)?,
+            exclude: build_glob_set(&node.exclude)?,
+        })
+    }
+
+    pub fn path(&self) -> &AbsPath {
+        &self.path
+    }
+
+    /// Whether `path`, a directory somewhere under this root, should be
+    /// descended into while walking. Only `exclude` is consulted here: an
+    /// `include` pattern like `src/**/*.lua` can't be evaluated against an
+    /// intermediate directory, so pruning on it would risk skipping
+    /// directories that do contain matching files deeper down.
+    pub fn should_descend(&self, path: &AbsPath) -> bool {
+        let relative = match self.relative(path) {
+            Some(relative) => relative,
+            None => return false,
+        };
+
+        !self.exclude.is_match(relative)
+    }
+
+    /// Whether `path`, a file somewhere under this root, should be synced.
+    pub fn includes_file(&self, path: &AbsPath) -> bool {
+        let relative = match self.relative(path) {
+            Some(relative) => relative,
+            None => return false,
+        };
+
+        let included = self.include.is_empty() || self.include.is_match(relative);
+        let excluded = self.exclude.is_match(relative);
+
+        included && !excluded
+    }
+
+    /// `path` made relative to this root, or `None` if `path` isn't actually
+    /// under this root at all (e.g. it belongs to a sibling root in a
+    /// multi-root project). Globs are only ever meant to be matched against
+    /// root-relative paths, so a path from a different root has no sensible
+    /// relative form to fall back to.
+    fn relative<'a>(&self, path: &'a AbsPath) -> Option<&'a std::path::Path> {
+        if !path.as_path().starts_with(self.path.as_path()) {
+            return None;
+        }
+
+        path.as_path().strip_prefix(self.path.as_path()).ok()
+    }
+
+    /// Walks this root's subtree of `imfs`, pruning excluded directories via
+    /// `should_descend` and collecting only the file entries that
+    /// `includes_file` accepts. This is the actual call site that makes the
+    /// `include`/`exclude` globs take effect: nothing outside what this
+    /// returns should be handed to a `SnapshotMiddleware`.
+    pub fn walk_included_files<F: ImfsFetcher>(
+        &self,
+        imfs: &mut Imfs<F>,
+    ) -> FsResult<Vec<ImfsEntry>> {
+        let mut included = Vec::new();
+
+        imfs.walk_filtered(
+            &self.path,
+            &mut |path| self.should_descend(path),
+            &mut |entry| {
+                if entry.is_file && self.includes_file(entry.path()) {
+                    included.push(entry.clone());
+                }
+            },
+        )?;
+
+        Ok(included)
+    }
+
+    /// Filters the paths that `M::change_affects_paths` reports down to the
+    /// ones this root actually syncs, so a change to a file outside every
+    /// `include` pattern (or inside an `exclude` one) doesn't trigger a
+    /// rebuild.
+    pub fn filter_changed_paths<M: SnapshotMiddleware>(&self, path: &AbsPath) -> Vec<AbsPathBuf> {
+        M::change_affects_paths(path)
+            .into_iter()
+            .filter(|affected| self.includes_file(affected))
+            .collect()
+    }
+}
+
+fn build_glob_set(patterns: &[String]) -> Result<GlobSet, InvalidGlob> {
+    let mut builder = GlobSetBuilder::new();
+
+    for pattern in patterns {
+        let glob = Glob::new(pattern).map_err(|source| InvalidGlob {
+            pattern: pattern.clone(),
+            source,
+        })?;
+        builder.add(glob);
+    }
+
+    builder.build().map_err(|source| InvalidGlob {
+        pattern: patterns.join(", "),
+        source,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn root(include: &[&str], exclude: &[&str]) -> ResolvedRoot {
+        let node = ProjectNode {
+            path: Some(PathBuf::from("src")),
+            include: include.iter().map(|s| s.to_string()).collect(),
+            exclude: exclude.iter().map(|s| s.to_string()).collect(),
+            children: HashMap::new(),
+        };
+
+        ResolvedRoot::new(AbsPathBuf::assert("/project/src"), &node).unwrap()
+    }
+
+    #[test]
+    fn empty_include_means_everything() {
+        let root = root(&[], &[]);
+
+        assert!(root.includes_file(&AbsPathBuf::assert("/project/src/init.lua")));
+    }
+
+    #[test]
+    fn include_narrows_to_matching_files() {
+        let root = root(&["**/*.lua"], &[]);
+
+        assert!(root.includes_file(&AbsPathBuf::assert("/project/src/init.lua")));
+        assert!(!root.includes_file(&AbsPathBuf::assert("/project/src/data.json")));
+    }
+
+    #[test]
+    fn exclude_wins_over_include() {
+        let root = root(&["**/*.lua"], &["**/*.spec.lua"]);
+
+        assert!(root.includes_file(&AbsPathBuf::assert("/project/src/init.lua")));
+        assert!(!root.includes_file(&AbsPathBuf::assert("/project/src/init.spec.lua")));
+    }
+
+    #[test]
+    fn should_descend_prunes_excluded_directories() {
+        let root = root(&["**/*.lua"], &["**/generated"]);
+
+        assert!(root.should_descend(&AbsPathBuf::assert("/project/src/components")));
+        assert!(!root.should_descend(&AbsPathBuf::assert("/project/src/generated")));
+    }
+
+    #[test]
+    fn paths_outside_the_root_are_never_included_or_descended() {
+        let root = root(&[], &[]);
+
+        // Not under `/project/src` at all, so even the "empty include means
+        // everything" rule must not kick in here.
+        assert!(!root.includes_file(&AbsPathBuf::assert("/project/other/init.lua")));
+        assert!(!root.should_descend(&AbsPathBuf::assert("/project/other")));
+    }
+
+    #[derive(Default)]
+    struct FakeFetcher {
+        dirs: HashMap<AbsPathBuf, Vec<AbsPathBuf>>,
+        files: HashMap<AbsPathBuf, Vec<u8>>,
+    }
+
+    impl ImfsFetcher for FakeFetcher {
+        fn read_metadata(&mut self, path: &AbsPath) -> FsResult<crate::imfs::new::ImfsMetadata> {
+            if self.files.contains_key(path) {
+                Ok(crate::imfs::new::ImfsMetadata { is_file: true })
+            } else if self.dirs.contains_key(path) {
+                Ok(crate::imfs::new::ImfsMetadata { is_file: false })
+            } else {
+                Err(crate::imfs::FsError::new(
+                    path.to_path_buf().into_path_buf(),
+                    not_found(),
+                ))
+            }
+        }
+
+        fn read_children(&mut self, path: &AbsPath) -> FsResult<Vec<AbsPathBuf>> {
+            Ok(self.dirs.get(path).cloned().unwrap_or_default())
+        }
+
+        fn read_contents(&mut self, path: &AbsPath) -> FsResult<Vec<u8>> {
+            self.files
+                .get(path)
+                .cloned()
+                .ok_or_else(|| crate::imfs::FsError::new(path.to_path_buf().into_path_buf(), not_found()))
+        }
+
+        fn read_contents_at_hash(
+            &mut self,
+            _hash: &crate::imfs::store::ContentHash,
+        ) -> FsResult<std::sync::Arc<Vec<u8>>> {
+            Err(crate::imfs::FsError::new(PathBuf::new(), not_found()))
+        }
+
+        fn watch(&mut self, _path: &AbsPath) {}
+        fn unwatch(&mut self, _path: &AbsPath) {}
+    }
+
+    fn not_found() -> std::io::Error {
+        std::io::Error::new(std::io::ErrorKind::NotFound, "path does not exist")
+    }
+
+    #[test]
+    fn walk_included_files_prunes_excluded_dirs_and_applies_include() {
+        let root_path = AbsPathBuf::assert("/project/src");
+        let components = AbsPathBuf::assert("/project/src/components");
+        let generated = AbsPathBuf::assert("/project/src/generated");
+        let init = AbsPathBuf::assert("/project/src/components/init.lua");
+        let data = AbsPathBuf::assert("/project/src/components/data.json");
+        let generated_file = AbsPathBuf::assert("/project/src/generated/init.lua");
+
+        let mut fetcher = FakeFetcher::default();
+        fetcher.dirs.insert(
+            root_path.clone(),
+            vec![components.clone(), generated.clone()],
+        );
+        fetcher
+            .dirs
+            .insert(components.clone(), vec![init.clone(), data.clone()]);
+        fetcher
+            .dirs
+            .insert(generated.clone(), vec![generated_file.clone()]);
+        fetcher.files.insert(init.clone(), b"return {}".to_vec());
+        fetcher.files.insert(data.clone(), b"{}".to_vec());
+        fetcher
+            .files
+            .insert(generated_file.clone(), b"return {}".to_vec());
+
+        let mut imfs = Imfs::new(fetcher);
+        let root = root_with_path(root_path, &["**/*.lua"], &["**/generated"]);
+
+        let included = root.walk_included_files(&mut imfs).unwrap();
+        let included_paths: Vec<_> = included
+            .iter()
+            .map(|entry| entry.path().as_path().to_path_buf())
+            .collect();
+
+        // `components/data.json` is dropped by `include`, and everything
+        // under `generated/` is pruned before it's ever read, so the
+        // excluded file never shows up even though the fetcher has it.
+        assert_eq!(
+            included_paths,
+            vec![PathBuf::from("/project/src/components/init.lua")]
+        );
+    }
+
+    fn root_with_path(path: AbsPathBuf, include: &[&str], exclude: &[&str]) -> ResolvedRoot {
+        let node = ProjectNode {
+            path: Some(PathBuf::from(".")),
+            include: include.iter().map(|s| s.to_string()).collect(),
+            exclude: exclude.iter().map(|s| s.to_string()).collect(),
+            children: HashMap::new(),
+        };
+
+        ResolvedRoot::new(path, &node).unwrap()
+    }
+
+    struct RenamingMiddleware;
+
+    impl SnapshotMiddleware for RenamingMiddleware {
+        fn from_imfs<F: ImfsFetcher>(
+            _imfs: &mut Imfs<F>,
+            _entry: &ImfsEntry,
+        ) -> crate::snapshot_middleware::SnapshotInstanceResult<'static> {
+            Ok(None)
+        }
+
+        fn from_instance(
+            _tree: &rbx_dom_weak::RbxTree,
+            _id: rbx_dom_weak::RbxId,
+        ) -> crate::snapshot_middleware::SnapshotFileResult {
+            None
+        }
+
+        fn change_affects_paths(path: &AbsPath) -> Vec<AbsPathBuf> {
+            vec![
+                path.to_path_buf(),
+                AbsPathBuf::assert("/project/src/components/sibling.lua"),
+                AbsPathBuf::assert("/project/src/components/data.json"),
+                AbsPathBuf::assert("/project/src/generated/init.lua"),
+            ]
+        }
+    }
+
+    #[test]
+    fn filter_changed_paths_drops_paths_outside_include_or_inside_exclude() {
+        let root = root_with_path(
+            AbsPathBuf::assert("/project/src"),
+            &["**/*.lua"],
+            &["**/generated/**"],
+        );
+
+        let affected = root.filter_changed_paths::<RenamingMiddleware>(&AbsPathBuf::assert(
+            "/project/src/components/init.lua",
+        ));
+
+        assert_eq!(
+            affected,
+            vec![
+                AbsPathBuf::assert("/project/src/components/init.lua"),
+                AbsPathBuf::assert("/project/src/components/sibling.lua"),
+            ]
+        );
+    }
+}
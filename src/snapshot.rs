@@ -0,0 +1,15 @@
+use std::borrow::Cow;
+
+use rbx_dom_weak::{RbxId, RbxValue};
+
+/// A lightweight, owned-or-borrowed description of an instance, produced by
+/// walking the `Imfs` or an `RbxTree` and consumed when building up a real
+/// `RbxTree` for a session.
+#[derive(Debug, Clone)]
+pub struct InstanceSnapshot<'a> {
+    pub snapshot_id: Option<RbxId>,
+    pub name: Cow<'a, str>,
+    pub class_name: Cow<'a, str>,
+    pub properties: Vec<(String, RbxValue)>,
+    pub children: Vec<InstanceSnapshot<'a>>,
+}
@@ -1,8 +1,7 @@
-use std::path::{Path, PathBuf};
-
 use rbx_dom_weak::{RbxId, RbxTree};
 
 use crate::{
+    abs_path::{AbsPath, AbsPathBuf},
     imfs::{
         new::{Imfs, ImfsEntry, ImfsFetcher, ImfsSnapshot},
         FsResult,
@@ -14,6 +13,13 @@ pub type SnapshotInstanceResult<'a> = FsResult<Option<InstanceSnapshot<'a>>>;
 pub type SnapshotFileResult = Option<(String, ImfsSnapshot)>;
 
 pub trait SnapshotMiddleware {
+    /// Builds an `InstanceSnapshot` from the contents of an `ImfsEntry`.
+    ///
+    /// Implementations should fetch file bodies through `imfs.contents(entry)`
+    /// rather than reading `entry` directly; this lets `Imfs` serve bytes out
+    /// of its content-addressed store (deduplicated by hash) instead of
+    /// re-reading or re-allocating a buffer for every path that happens to
+    /// share the same contents.
     fn from_imfs<F: ImfsFetcher>(
         imfs: &mut Imfs<F>,
         entry: &ImfsEntry,
@@ -21,7 +27,13 @@ pub trait SnapshotMiddleware {
 
     fn from_instance(tree: &RbxTree, id: RbxId) -> SnapshotFileResult;
 
-    fn change_affects_paths(path: &Path) -> Vec<PathBuf> {
+    /// Paths that should be treated as affected by a change to `path`; by
+    /// default just `path` itself (other middlewares, like `json_model`, may
+    /// add siblings whose contents depend on it). Callers should route the result through
+    /// `project::ResolvedRoot::filter_changed_paths` before acting on it, so
+    /// a change under an excluded path, or outside every `include` pattern,
+    /// is dropped rather than triggering a rebuild.
+    fn change_affects_paths(path: &AbsPath) -> Vec<AbsPathBuf> {
         vec![path.to_path_buf()]
     }
 }
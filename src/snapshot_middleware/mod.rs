@@ -0,0 +1,3 @@
+mod middleware;
+
+pub use middleware::*;